@@ -7,15 +7,56 @@ use crate::clang_info::ClangInfo;
 use anyhow::anyhow;
 use anyhow::Context;
 use anyhow::Result;
+use bindgen::callbacks::ParseCallbacks;
 use glob::glob;
 use libbpf_cargo::SkeletonBuilder;
+use libbpf_rs::btf::Btf;
+use libbpf_rs::btf_dump::BtfDump;
 use libbpf_rs::Linker;
+use quote::quote;
+use std::cell::RefCell;
 use std::collections::BTreeSet;
 use std::env;
+use std::fs;
+use std::io::Write;
 use std::path::Path;
 use std::path::PathBuf;
+use std::process::Command;
+use syn::Fields;
+use syn::Item;
+use syn::Type;
+
+/// Environment variable pointing at a BTF blob (typically
+/// `/sys/kernel/btf/vmlinux`) to generate `vmlinux.h` from, in place of the
+/// bundled header snapshot. See [`BpfBuilder::enable_btf_vmlinux`].
+const BPF_VMLINUX_BTF: &str = "BPF_VMLINUX_BTF";
+
+/// Environment variable overriding the `bpftool` command used to generate
+/// subskeletons. See [`BpfBuilder::enable_subskel`].
+const BPF_BPFTOOL: &str = "BPF_BPFTOOL";
+
+/// The default BPF ISA level targeted if [`BpfBuilder::set_cpu_version`]
+/// isn't called.
+const DEFAULT_CPU_VERSION: u8 = 3;
+
+/// Target endianness for the generated BPF object, following the
+/// `bpfel`/`bpfeb` split target model used elsewhere in the BPF ecosystem.
+/// See [`BpfBuilder::set_target_endianness`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Endianness {
+    Little,
+    Big,
+}
+
+impl Endianness {
+    fn cflag(&self) -> &'static str {
+        match self {
+            Endianness::Little => "-mlittle-endian",
+            Endianness::Big => "-mbig-endian",
+        }
+    }
+}
 
-#[derive(Debug)]
 /// # Build helpers for sched_ext schedulers with Rust userspace component
 ///
 /// This is to be used from `build.rs` of a cargo project which implements a
@@ -146,6 +187,11 @@ use std::path::PathBuf;
 ///
 /// - `BPF_CLANG`: The clang command to use. (Default: `clang`)
 ///
+/// - `BPF_BPFTOOL`: The `bpftool` command used to generate subskeletons
+///   with `enable_subskel`. (Default: `bpftool`) Only needed if
+///   `enable_subskel` is used; `bpftool` must otherwise be installed and
+///   on `$PATH`.
+///
 /// - `BPF_CFLAGS`: Compiler flags to use when building BPF source code. If
 ///   specified, the flags from this variable are the only flags passed to
 ///   the compiler. `BpfBuilder` won't generate any flags including `-I`
@@ -166,6 +212,10 @@ use std::path::PathBuf;
 /// - `RUSTFLAGS`: This is a generic `cargo` flag and can be useful for
 ///   specifying extra linker flags.
 ///
+/// - `BPF_VMLINUX_BTF`: Path to a BTF blob (e.g. `/sys/kernel/btf/vmlinux`)
+///   to generate `vmlinux.h` from, in place of the bundled snapshot. See
+///   [`BpfBuilder::enable_btf_vmlinux`].
+///
 /// A common case for using the above flags is using the latest `libbpf`
 /// from the kernel tree. Let's say the kernel tree is at `$KERNEL` and
 /// `libbpf`. The following builds `libbpf` shipped with the kernel:
@@ -190,6 +240,32 @@ pub struct BpfBuilder {
 
     intf_input_output: Option<(String, String)>,
     skel_input_name: Option<(String, String)>,
+    btf_vmlinux_path: Option<PathBuf>,
+    subskel_name: Option<String>,
+    intf_rustified_enums: Vec<String>,
+    intf_bitfield_enums: Vec<String>,
+    intf_parse_callbacks: RefCell<Option<Box<dyn ParseCallbacks>>>,
+    intf_gen_read_accessors: bool,
+    cpu_version: u8,
+}
+
+impl std::fmt::Debug for BpfBuilder {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("BpfBuilder")
+            .field("clang", &self.clang)
+            .field("cflags", &self.cflags)
+            .field("out_dir", &self.out_dir)
+            .field("sources", &self.sources)
+            .field("intf_input_output", &self.intf_input_output)
+            .field("skel_input_name", &self.skel_input_name)
+            .field("btf_vmlinux_path", &self.btf_vmlinux_path)
+            .field("subskel_name", &self.subskel_name)
+            .field("intf_rustified_enums", &self.intf_rustified_enums)
+            .field("intf_bitfield_enums", &self.intf_bitfield_enums)
+            .field("intf_gen_read_accessors", &self.intf_gen_read_accessors)
+            .field("cpu_version", &self.cpu_version)
+            .finish_non_exhaustive()
+    }
 }
 
 impl BpfBuilder {
@@ -266,9 +342,182 @@ impl BpfBuilder {
             sources: BTreeSet::new(),
             intf_input_output: None,
             skel_input_name: None,
+            btf_vmlinux_path: None,
+            subskel_name: None,
+            intf_rustified_enums: Vec::new(),
+            intf_bitfield_enums: Vec::new(),
+            intf_parse_callbacks: RefCell::new(None),
+            intf_gen_read_accessors: false,
+            cpu_version: DEFAULT_CPU_VERSION,
         })
     }
 
+    /// Set the target endianness for the generated BPF object, injecting
+    /// `-mlittle-endian`/`-mbig-endian` into `cflags`, along with the
+    /// matching `-D__TARGET_ARCH_<target_arch>` CO-RE macro that headers
+    /// like `bpf_tracing.h` switch on. `target_arch` is the architecture
+    /// actually being targeted (e.g. `"powerpc"`, `"mips"`) - it is *not*
+    /// derived from `self.clang.kernel_target()`, since that reflects the
+    /// build host, which is generally a different architecture than the
+    /// cross-compilation target this method exists for. Passing a
+    /// `target_arch`/`endianness` pair that the target doesn't actually
+    /// support (e.g. `"x86"` with `Endianness::Big`) is a caller error;
+    /// this builder doesn't validate it, same as the rest of `BpfBuilder`'s
+    /// setters. If `cflags` already define `__TARGET_ARCH_*` from any
+    /// source (e.g. `BPF_EXTRA_CFLAGS_POST_INCL`, `BPF_CFLAGS` or an
+    /// earlier call to this method), that definition is left alone and no
+    /// `-D__TARGET_ARCH_<target_arch>` is pushed here.
+    ///
+    /// This flows through to both the `SkeletonBuilder` clang invocation
+    /// and the `bindgen` `-target bpf` args, so the generated bindings
+    /// match the chosen endianness.
+    pub fn set_target_endianness(
+        &mut self,
+        endianness: Endianness,
+        target_arch: &str,
+    ) -> &mut Self {
+        self.cflags.push(endianness.cflag().into());
+        if !self
+            .cflags
+            .iter()
+            .any(|f| f.starts_with("-D__TARGET_ARCH_"))
+        {
+            self.cflags.push(format!("-D__TARGET_ARCH_{target_arch}"));
+        }
+        self
+    }
+
+    /// Set the BPF ISA level (`-mcpu=vN`) to target, for pinning the
+    /// generated bytecode to what an older kernel or its verifier
+    /// supports. Defaults to `v3`. Calling this more than once replaces
+    /// the previously requested version rather than adding another
+    /// `-mcpu=` flag.
+    pub fn set_cpu_version(&mut self, version: u8) -> &mut Self {
+        self.cpu_version = version;
+        self
+    }
+
+    /// `cflags` plus the single `-mcpu=vN` flag for the currently
+    /// requested `cpu_version`. Used instead of baking `-mcpu=` into
+    /// `cflags` directly so that `set_cpu_version` can replace rather than
+    /// accumulate it.
+    fn cflags_with_cpu_version(&self) -> Vec<String> {
+        let mut cflags = self.cflags.clone();
+        cflags.push(format!("-mcpu=v{}", self.cpu_version));
+        cflags
+    }
+
+    /// Enable generation of a *subskeleton* binding, `@name`, for the
+    /// linked BPF object produced by `compile_link_gen`. Unlike the full
+    /// skeleton produced by `enable_skel`, a subskeleton only exposes the
+    /// maps, progs and globals of the object without owning its
+    /// load/attach lifecycle - the equivalent of `bpftool gen subskeleton
+    /// <linked.o> name <name>`. This is useful when one crate builds a
+    /// shared BPF library object and another crate links and loads it but
+    /// still needs bindings for the maps and progs the shared library
+    /// defines.
+    pub fn enable_subskel(&mut self, name: &str) -> &mut Self {
+        self.subskel_name = Some(name.into());
+        self
+    }
+
+    fn gen_subskel(&self, linkobj: &Path) -> Result<()> {
+        let name = match &self.subskel_name {
+            Some(name) => name,
+            None => return Ok(()),
+        };
+
+        let subskel_path = self.out_dir.join(format!("{}_subskel.rs", name));
+
+        let bpftool = env::var(BPF_BPFTOOL).unwrap_or_else(|_| "bpftool".into());
+
+        let output = Command::new(&bpftool)
+            .arg("gen")
+            .arg("subskeleton")
+            .arg(linkobj)
+            .arg("name")
+            .arg(name)
+            .output()
+            .with_context(|| {
+                format!(
+                    "Failed to run `{} gen subskeleton` - is bpftool installed and in $PATH? \
+                     Override with the {} environment variable",
+                    bpftool, BPF_BPFTOOL
+                )
+            })?;
+
+        if !output.status.success() {
+            return Err(anyhow!(
+                "`{} gen subskeleton` failed: {}",
+                bpftool,
+                String::from_utf8_lossy(&output.stderr)
+            ));
+        }
+
+        fs::write(&subskel_path, &output.stdout)
+            .with_context(|| format!("Failed to write {:?}", &subskel_path))
+    }
+
+    /// Enable generating `vmlinux.h` from a kernel's BTF instead of relying
+    /// on the `vmlinux.h` snapshots bundled with this crate. By default
+    /// `/sys/kernel/btf/vmlinux` is read; `@btf_path`, if given, points at a
+    /// BTF blob to dump instead. This can also be enabled without a source
+    /// change by pointing the `BPF_VMLINUX_BTF` environment variable at a
+    /// BTF blob.
+    ///
+    /// The generated header is written into the same `arch/<target>`
+    /// include directory the bundled snapshots are unpacked into, so it
+    /// supersedes them on the `-I` search path without any further
+    /// `cflags` changes. This is useful for building a scheduler that
+    /// matches exactly whatever kernel BTF is present rather than the
+    /// closest bundled snapshot.
+    pub fn enable_btf_vmlinux(&mut self, btf_path: Option<&str>) -> &mut Self {
+        self.btf_vmlinux_path = Some(
+            btf_path
+                .map(PathBuf::from)
+                .unwrap_or_else(|| PathBuf::from("/sys/kernel/btf/vmlinux")),
+        );
+        self
+    }
+
+    fn generate_btf_vmlinux(&self) -> Result<()> {
+        let btf_path = match &self.btf_vmlinux_path {
+            Some(p) => p.clone(),
+            None => match env::var(BPF_VMLINUX_BTF) {
+                Ok(v) => PathBuf::from(v),
+                _ => return Ok(()),
+            },
+        };
+
+        let btf = Btf::from_path(&btf_path)
+            .with_context(|| format!("Failed to open BTF at {:?}", &btf_path))?;
+        let header = BtfDump::new(&btf)
+            .context("Failed to set up BTF dump")?
+            .dump_all_types()
+            .context("Failed to dump vmlinux.h from BTF")?;
+
+        let vmlinux_h = self
+            .out_dir
+            .join("scx_utils-bpf_h")
+            .join("arch")
+            .join(self.clang.kernel_target().unwrap())
+            .join("vmlinux.h");
+
+        // `install_bpf_h` is what normally creates this directory tree, but
+        // it's skipped in `new()` whenever `BPF_CFLAGS` is set, so it can't
+        // be assumed to exist here.
+        if let Some(parent) = vmlinux_h.parent() {
+            fs::create_dir_all(parent).with_context(|| format!("Failed to create {:?}", parent))?;
+        }
+
+        fs::write(&vmlinux_h, header)
+            .with_context(|| format!("Failed to write {:?}", &vmlinux_h))?;
+
+        println!("cargo:rerun-if-changed={}", btf_path.display());
+
+        Ok(())
+    }
+
     /// Enable generation of header bindings using `bindgen`. `@input` is
     /// the `.h` file defining the constants and types to be shared between
     /// BPF and Rust components. `@output` is the `.rs` file to be
@@ -278,6 +527,43 @@ impl BpfBuilder {
         self
     }
 
+    /// Map the given C enums to real Rust `enum`s rather than plain
+    /// integer constants when generating bindings with `enable_intf`. See
+    /// `bindgen::Builder::rustified_enum`.
+    pub fn intf_rustified_enums(&mut self, enums: &[&str]) -> &mut Self {
+        self.intf_rustified_enums
+            .extend(enums.iter().map(|e| e.to_string()));
+        self
+    }
+
+    /// Map the given C enums to bitflags-style Rust types rather than
+    /// plain integer constants when generating bindings with
+    /// `enable_intf`. See `bindgen::Builder::bitfield_enum`.
+    pub fn intf_bitfield_enums(&mut self, enums: &[&str]) -> &mut Self {
+        self.intf_bitfield_enums
+            .extend(enums.iter().map(|e| e.to_string()));
+        self
+    }
+
+    /// Escape hatch for callers that need finer control over the
+    /// `bindgen::Builder` used by `enable_intf` than `intf_rustified_enums`
+    /// and `intf_bitfield_enums` provide, e.g. custom
+    /// `EnumVariantCustomBehavior`.
+    pub fn intf_parse_callbacks(&mut self, callbacks: Box<dyn ParseCallbacks>) -> &mut Self {
+        self.intf_parse_callbacks = RefCell::new(Some(callbacks));
+        self
+    }
+
+    /// After generating bindings with `enable_intf`, walk the generated
+    /// structs and emit safe `read_*` accessor methods for integer fields
+    /// (`__u8`/`__u16`/`__u32`/`__u64` and byte arrays of matching size).
+    /// This saves userspace code from manually pointer-casting bytes
+    /// copied out of a BPF map or ringbuf into the generated struct types.
+    pub fn intf_gen_read_accessors(&mut self, enable: bool) -> &mut Self {
+        self.intf_gen_read_accessors = enable;
+        self
+    }
+
     /// Enable compilation of BPF code and generation of the skeleton and
     /// its Rust bindings. `@input` is the `.bpf.c` file containing the BPF
     /// source code and `@output` is the `.rs` file to be generated.
@@ -304,26 +590,156 @@ impl BpfBuilder {
             None => return Ok(()),
         };
 
+        let cflags = self.cflags_with_cpu_version();
+
         // The bindgen::Builder is the main entry point to bindgen, and lets
         // you build up options for the resulting bindings.
-        let bindings = bindgen::Builder::default()
+        let mut builder = bindgen::Builder::default()
             // Should run clang with the same -I options as BPF compilation.
-            .clang_args(
-                self.cflags
-                    .iter()
-                    .chain(["-target".into(), "bpf".into()].iter()),
-            )
+            .clang_args(cflags.iter().chain(["-target".into(), "bpf".into()].iter()))
             // The input header we would like to generate bindings for.
             .header(input)
             // Tell cargo to invalidate the built crate whenever any of the
             // included header files changed.
-            .parse_callbacks(Box::new(bindgen::CargoCallbacks::new()))
-            .generate()
-            .context("Unable to generate bindings")?;
+            .parse_callbacks(Box::new(bindgen::CargoCallbacks::new()));
+
+        for e in self.intf_rustified_enums.iter() {
+            builder = builder.rustified_enum(e);
+        }
+
+        for e in self.intf_bitfield_enums.iter() {
+            builder = builder.bitfield_enum(e);
+        }
+
+        if let Some(callbacks) = self.intf_parse_callbacks.borrow_mut().take() {
+            builder = builder.parse_callbacks(callbacks);
+        }
+
+        let bindings = builder.generate().context("Unable to generate bindings")?;
 
+        let output = self.out_dir.join(output);
         bindings
-            .write_to_file(self.out_dir.join(output))
-            .context("Couldn't write bindings")
+            .write_to_file(&output)
+            .context("Couldn't write bindings")?;
+
+        self.gen_read_accessors(&output)
+    }
+
+    fn read_accessor_type(ty: &Type) -> Option<(&'static str, usize)> {
+        match ty {
+            // bindgen keeps kernel-style typedefs (`__u32`, ...) as distinct
+            // type aliases rather than rewriting fields to the plain Rust
+            // primitive, so both spellings need to be recognized here.
+            Type::Path(tp) => match tp.path.segments.last()?.ident.to_string().as_str() {
+                "u8" | "__u8" => Some(("u8", 1)),
+                "u16" | "__u16" => Some(("u16", 2)),
+                "u32" | "__u32" => Some(("u32", 4)),
+                "u64" | "__u64" => Some(("u64", 8)),
+                _ => None,
+            },
+            Type::Array(arr) => {
+                let (elem_ty, _) = Self::read_accessor_type(&arr.elem)?;
+                if elem_ty != "u8" {
+                    return None;
+                }
+                let len = match &arr.len {
+                    syn::Expr::Lit(syn::ExprLit {
+                        lit: syn::Lit::Int(n),
+                        ..
+                    }) => n.base10_parse::<usize>().ok()?,
+                    _ => return None,
+                };
+                match len {
+                    2 => Some(("u16", 2)),
+                    4 => Some(("u32", 4)),
+                    8 => Some(("u64", 8)),
+                    _ => None,
+                }
+            }
+            _ => None,
+        }
+    }
+
+    /// Parse `@src` as a Rust source file and emit `read_*` accessor
+    /// methods for the integer and byte-array fields of every struct it
+    /// defines. Kept free of any `BpfBuilder` state so it can be unit
+    /// tested directly against a source snippet.
+    fn generate_read_accessors(src: &str) -> Result<proc_macro2::TokenStream> {
+        let file =
+            syn::parse_file(src).context("Failed to parse source for accessor generation")?;
+
+        let mut accessors = proc_macro2::TokenStream::new();
+
+        for item in &file.items {
+            let Item::Struct(item_struct) = item else {
+                continue;
+            };
+            let Fields::Named(fields) = &item_struct.fields else {
+                continue;
+            };
+
+            let struct_name = &item_struct.ident;
+            let mut methods = proc_macro2::TokenStream::new();
+
+            for field in &fields.named {
+                let Some(field_name) = &field.ident else {
+                    continue;
+                };
+                let Some((rust_ty, _)) = Self::read_accessor_type(&field.ty) else {
+                    continue;
+                };
+
+                let method_name = quote::format_ident!("read_{}", field_name);
+                let rust_ty = quote::format_ident!("{}", rust_ty);
+
+                let body = if matches!(&field.ty, Type::Array(_)) {
+                    quote! { #rust_ty::from_ne_bytes(self.#field_name) }
+                } else {
+                    quote! { self.#field_name }
+                };
+
+                methods.extend(quote! {
+                    pub fn #method_name(&self) -> #rust_ty {
+                        #body
+                    }
+                });
+            }
+
+            if !methods.is_empty() {
+                accessors.extend(quote! {
+                    impl #struct_name {
+                        #methods
+                    }
+                });
+            }
+        }
+
+        Ok(accessors)
+    }
+
+    /// Append `read_*` accessor methods for the integer and byte-array
+    /// fields of every struct `bindgen` generated into `@output`, if
+    /// `intf_gen_read_accessors` was enabled.
+    fn gen_read_accessors(&self, output: &Path) -> Result<()> {
+        if !self.intf_gen_read_accessors {
+            return Ok(());
+        }
+
+        let src = fs::read_to_string(output)
+            .with_context(|| format!("Failed to read {:?} for accessor generation", output))?;
+        let accessors = Self::generate_read_accessors(&src)
+            .with_context(|| format!("Failed to generate accessors for {:?}", output))?;
+
+        if accessors.is_empty() {
+            return Ok(());
+        }
+
+        let mut f = fs::OpenOptions::new()
+            .append(true)
+            .open(output)
+            .with_context(|| format!("Failed to reopen {:?}", output))?;
+        writeln!(f, "\n{}", accessors)
+            .with_context(|| format!("Failed to append accessors to {:?}", output))
     }
 
     pub fn add_source(&mut self, input: &str) -> &mut Self {
@@ -337,25 +753,55 @@ impl BpfBuilder {
             None => return Ok(()),
         };
 
+        self.generate_btf_vmlinux()?;
+
+        let cflags = self.cflags_with_cpu_version();
+        let mut deps = BTreeSet::new();
+
         let linkobj = self.out_dir.join(format!("{}.bpf.o", name));
         let mut linker = Linker::new(&linkobj)?;
 
         for filename in self.sources.iter() {
-            let obj = self.out_dir.join(name.replace(".bpf.c", ".bpf.o"));
+            let src_path = PathBuf::from(filename);
+            let obj_name = src_path
+                .file_name()
+                .ok_or(anyhow!("Source {:?} doesn't have a file name", src_path))?
+                .to_str()
+                .ok_or(anyhow!("Source {:?} isn't a valid UTF-8 string", src_path))?
+                .replace(".bpf.c", ".bpf.o");
+            let obj = self.out_dir.join(obj_name);
 
             SkeletonBuilder::new()
                 .debug(true)
                 .source(filename)
                 .obj(&obj)
                 .clang(&self.clang.clang)
-                .clang_args(&self.cflags)
+                .clang_args(&cflags)
                 .build()?;
 
             linker.add_file(&obj)?;
+
+            deps.insert(filename.to_string());
+
+            let dir = src_path
+                .parent()
+                .ok_or(anyhow!("Source {:?} doesn't have parent dir", src_path))?
+                .to_str()
+                .ok_or(anyhow!("Parent dir of {:?} isn't a UTF-8 string", src_path))?;
+
+            for path in glob(&format!("{}/*.h", dir))?.filter_map(Result::ok) {
+                deps.insert(
+                    path.to_str()
+                        .ok_or(anyhow!("Path {:?} is not a valid string", path))?
+                        .to_string(),
+                );
+            }
         }
 
         linker.link()?;
 
+        self.gen_subskel(&linkobj)?;
+
         self.bindgen_bpf_intf()?;
 
         let skel_path = self.out_dir.join(format!("{}_skel.rs", name));
@@ -363,10 +809,10 @@ impl BpfBuilder {
         SkeletonBuilder::new()
             .obj(&linkobj)
             .clang(&self.clang.clang)
-            .clang_args(&self.cflags)
+            .clang_args(&cflags)
             .generate(&skel_path)?;
 
-        self.gen_cargo_reruns(None)?;
+        self.gen_cargo_reruns(Some(&deps))?;
 
         Ok(())
     }
@@ -384,7 +830,7 @@ impl BpfBuilder {
             .source(input)
             .obj(&obj)
             .clang(&self.clang.clang)
-            .clang_args(&self.cflags)
+            .clang_args(&self.cflags_with_cpu_version())
             .build_and_generate(&skel_path)?;
 
         for line in String::from_utf8_lossy(output.stderr()).lines() {
@@ -411,6 +857,7 @@ impl BpfBuilder {
 
     fn gen_cargo_reruns(&self, dependencies: Option<&BTreeSet<String>>) -> Result<()> {
         println!("cargo:rerun-if-env-changed=BPF_CLANG");
+        println!("cargo:rerun-if-env-changed=BPF_BPFTOOL");
         println!("cargo:rerun-if-env-changed=BPF_CFLAGS");
         println!("cargo:rerun-if-env-changed=BPF_BASE_CFLAGS");
         println!("cargo:rerun-if-env-changed=BPF_EXTRA_CFLAGS_PRE_INCL");
@@ -438,6 +885,7 @@ impl BpfBuilder {
 
         self.input_insert_deps(&mut deps);
 
+        self.generate_btf_vmlinux()?;
         self.bindgen_bpf_intf()?;
         self.gen_bpf_skel(&mut deps)?;
         self.gen_cargo_reruns(Some(&deps))?;
@@ -447,6 +895,8 @@ impl BpfBuilder {
 
 #[cfg(test)]
 mod tests {
+    use std::env;
+
     use regex::Regex;
     use sscanf::sscanf;
 
@@ -458,6 +908,50 @@ mod tests {
         assert!(res.is_ok(), "Failed to create BpfBuilder ({:?})", &res);
     }
 
+    #[test]
+    fn test_set_cpu_version_replaces_rather_than_accumulates() {
+        let mut builder = super::BpfBuilder::new().unwrap();
+
+        builder.set_cpu_version(2);
+        builder.set_cpu_version(4);
+
+        let cflags = builder.cflags_with_cpu_version();
+        let mcpu_flags: Vec<_> = cflags.iter().filter(|f| f.starts_with("-mcpu=")).collect();
+
+        assert_eq!(mcpu_flags, vec!["-mcpu=v4"]);
+    }
+
+    #[test]
+    fn test_set_target_endianness_pushes_matching_arch_macro() {
+        let mut little = super::BpfBuilder::new().unwrap();
+        little.set_target_endianness(super::Endianness::Little, "powerpc");
+        assert!(little.cflags.contains(&"-mlittle-endian".to_string()));
+        assert!(little
+            .cflags
+            .contains(&"-D__TARGET_ARCH_powerpc".to_string()));
+
+        let mut big = super::BpfBuilder::new().unwrap();
+        big.set_target_endianness(super::Endianness::Big, "powerpc");
+        assert!(big.cflags.contains(&"-mbig-endian".to_string()));
+        assert!(big.cflags.contains(&"-D__TARGET_ARCH_powerpc".to_string()));
+    }
+
+    #[test]
+    fn test_set_target_endianness_respects_post_incl_override() {
+        env::set_var("BPF_EXTRA_CFLAGS_POST_INCL", "-D__TARGET_ARCH_arm64");
+        let mut builder = super::BpfBuilder::new().unwrap();
+        env::remove_var("BPF_EXTRA_CFLAGS_POST_INCL");
+
+        builder.set_target_endianness(super::Endianness::Little, "powerpc");
+
+        let arch_flags: Vec<_> = builder
+            .cflags
+            .iter()
+            .filter(|f| f.starts_with("-D__TARGET_ARCH_"))
+            .collect();
+        assert_eq!(arch_flags, vec!["-D__TARGET_ARCH_arm64"]);
+    }
+
     #[test]
     fn test_vmlinux_h_ver_sha1() {
         let clang_info = ClangInfo::new().unwrap();
@@ -497,4 +991,53 @@ mod tests {
 
         assert!(found);
     }
+
+    #[test]
+    fn test_read_accessor_type_matches_kernel_typedefs() {
+        for (src, want) in [
+            ("__u8", Some(("u8", 1))),
+            ("__u16", Some(("u16", 2))),
+            ("__u32", Some(("u32", 4))),
+            ("__u64", Some(("u64", 8))),
+            ("u32", Some(("u32", 4))),
+            ("[__u8; 4]", Some(("u32", 4))),
+            ("[__u8; 16]", None),
+            ("bool", None),
+        ] {
+            let ty: syn::Type = syn::parse_str(src).unwrap();
+            assert_eq!(
+                super::BpfBuilder::read_accessor_type(&ty),
+                want,
+                "mismatch for {src}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_generate_read_accessors_for_kernel_typedef_struct() {
+        let src = r#"
+            pub type __u8 = u8;
+            pub type __u32 = u32;
+            pub type __u64 = u64;
+
+            #[repr(C)]
+            pub struct bpf_map_value {
+                pub pid: __u32,
+                pub runtime: __u64,
+                pub flags: [__u8; 4],
+                pub name: [__u8; 16],
+            }
+        "#;
+
+        let accessors = super::BpfBuilder::generate_read_accessors(src).unwrap();
+        let rendered = accessors.to_string();
+
+        assert!(rendered.contains("read_pid"), "{rendered}");
+        assert!(rendered.contains("read_runtime"), "{rendered}");
+        assert!(rendered.contains("read_flags"), "{rendered}");
+        assert!(
+            !rendered.contains("read_name"),
+            "16-byte array shouldn't get an accessor: {rendered}"
+        );
+    }
 }